@@ -0,0 +1,437 @@
+use crate::route::{BasicRoute, Destination, RouteMode, SingleBasicRoute, SingleBasicRouteError};
+use crate::ClientId;
+use rumqtt::{ConnectionMethod, MqttOptions, SecurityOptions};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Declarative description of a bridge topology, loaded from a config file
+/// instead of being hardcoded in `main()`.
+///
+/// The file is made of two sections:
+///
+/// ```text
+/// [brokers]
+/// <name> <host> <port> [client_id=<id>] [user=<user>] [pass=<pass>] [ca=<path>] [cert=<path>] [key=<path>]
+///
+/// [routes]
+/// <src_client> <src_topic> <dest_client> <dest_topic> <qos> [mode=<mode>]
+/// ```
+///
+/// A broker connects over TLS when `ca=<path>` is given, loading that file as
+/// the CA certificate; `cert=`/`key=` optionally add a client certificate for
+/// mutual TLS and must be given together. With neither, the connection is
+/// plain TCP.
+///
+/// `mode=` picks how a route with several destinations forwards a message:
+/// `fanout` (the default) sends it to all of them; `hash`,
+/// `hash_segment:<index>` and `hash_payload:<offset>:<len>` consistently
+/// hash the topic, a level captured by a trailing `#` (levels matched by
+/// `+` are not captured), or a payload byte range to pick exactly one. All
+/// lines sharing a `<src_client>` and `<src_topic>` must agree on it.
+///
+/// `<name>` is how a broker is referred to from the `[routes]` section; it is
+/// resolved to the `ClientId` (its position in `mqtt_options`) at load time.
+/// Route lines reuse the grammar already parsed by
+/// [`SingleBasicRoute::from_string`].
+#[derive(Debug)]
+pub struct BridgeConfiguration {
+    pub mqtt_options: Vec<MqttOptions>,
+    pub routes: Vec<Vec<BasicRoute>>,
+}
+
+#[derive(Debug)]
+pub enum BrokerLineError {
+    NotEnoughArguments { required: u8, given: u8 },
+    InvalidPort(std::num::ParseIntError),
+    UnknownOption(String),
+    /// `cert=`/`key=` was given without the other; mutual TLS needs both.
+    IncompleteClientCert,
+    /// `cert=`/`key=` was given without `ca=`; a client cert only makes
+    /// sense on top of a TLS connection.
+    ClientCertWithoutCa,
+    TlsFile { path: String, error: std::io::Error },
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    UnexpectedSection { line: usize, name: String },
+    ContentBeforeSection { line: usize },
+    DuplicateBroker { line: usize, name: String },
+    Broker { line: usize, error: BrokerLineError },
+    Route { line: usize, error: SingleBasicRouteError },
+    /// Two route lines for the same source client and `source_topic`
+    /// disagree on `mode=`;
+    /// it's a property of the route as a whole, not of one destination.
+    ConflictingRouteMode { line: usize, topic: String },
+}
+
+enum Section {
+    Brokers,
+    Routes,
+}
+
+impl BridgeConfiguration {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let content = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        Self::from_str(&content)
+    }
+
+    fn from_str(content: &str) -> Result<Self, ConfigError> {
+        let mut section = None;
+        let mut broker_names: HashMap<String, ClientId> = HashMap::new();
+        let mut mqtt_options = vec![];
+        let mut single_routes = vec![];
+
+        for (i, raw_line) in content.lines().enumerate() {
+            let line_nb = i + 1;
+            let line = raw_line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                section = Some(match &line[1..line.len() - 1] {
+                    "brokers" => Section::Brokers,
+                    "routes" => Section::Routes,
+                    other => {
+                        return Err(ConfigError::UnexpectedSection {
+                            line: line_nb,
+                            name: other.to_string(),
+                        })
+                    }
+                });
+                continue;
+            }
+
+            match section {
+                None => return Err(ConfigError::ContentBeforeSection { line: line_nb }),
+                Some(Section::Brokers) => {
+                    let (name, options) = parse_broker_line(line)
+                        .map_err(|error| ConfigError::Broker { line: line_nb, error })?;
+                    if broker_names.contains_key(&name) {
+                        return Err(ConfigError::DuplicateBroker {
+                            line: line_nb,
+                            name,
+                        });
+                    }
+                    broker_names.insert(name, mqtt_options.len() as ClientId);
+                    mqtt_options.push(options);
+                }
+                Some(Section::Routes) => {
+                    // Resolved once every broker is known, see below.
+                    single_routes.push((line_nb, line.to_string()));
+                }
+            }
+        }
+
+        let mut routes = vec![vec![]; mqtt_options.len()];
+        for (line_nb, line) in single_routes {
+            let single = SingleBasicRoute::from_string(broker_names.clone(), &line)
+                .map_err(|error| ConfigError::Route { line: line_nb, error })?;
+            let source_topic_str = single.source_topic.to_string();
+            let dest = Destination {
+                topic: single.dest_topic,
+                client_id: single.dest_client_id,
+                qos: single.dest_qos,
+            };
+            let client_routes = &mut routes[single.source_client_id as usize];
+            match client_routes
+                .iter_mut()
+                .find(|route: &&mut BasicRoute| route.source_topic.to_string() == source_topic_str)
+            {
+                Some(route) => {
+                    if route.mode != single.mode {
+                        return Err(ConfigError::ConflictingRouteMode {
+                            line: line_nb,
+                            topic: source_topic_str,
+                        });
+                    }
+                    route.dests.push(dest);
+                }
+                None => client_routes.push(BasicRoute {
+                    source_topic: single.source_topic,
+                    dests: vec![dest],
+                    mode: single.mode,
+                }),
+            }
+        }
+
+        Ok(BridgeConfiguration {
+            mqtt_options,
+            routes,
+        })
+    }
+}
+
+fn parse_broker_line(line: &str) -> Result<(String, MqttOptions), BrokerLineError> {
+    let words: Vec<_> = line.split(' ').collect();
+    let min_arg_nb = 3;
+    if words.len() < min_arg_nb {
+        return Err(BrokerLineError::NotEnoughArguments {
+            required: min_arg_nb as u8,
+            given: words.len() as u8,
+        });
+    }
+
+    let name = words[0].to_string();
+    let host = words[1];
+    let port = words[2]
+        .parse::<u16>()
+        .map_err(BrokerLineError::InvalidPort)?;
+
+    let mut client_id = name.clone();
+    let mut credentials = None;
+    let mut ca_path = None;
+    let mut client_cert_path = None;
+    let mut client_key_path = None;
+    for option in &words[3..] {
+        if let Some(value) = option.strip_prefix("client_id=") {
+            client_id = value.to_string();
+        } else if let Some(value) = option.strip_prefix("user=") {
+            let pass = credentials.map(|(_, pass)| pass).unwrap_or_default();
+            credentials = Some((value.to_string(), pass));
+        } else if let Some(value) = option.strip_prefix("pass=") {
+            let user = credentials.map(|(user, _)| user).unwrap_or_default();
+            credentials = Some((user, value.to_string()));
+        } else if let Some(value) = option.strip_prefix("ca=") {
+            ca_path = Some(value.to_string());
+        } else if let Some(value) = option.strip_prefix("cert=") {
+            client_cert_path = Some(value.to_string());
+        } else if let Some(value) = option.strip_prefix("key=") {
+            client_key_path = Some(value.to_string());
+        } else {
+            return Err(BrokerLineError::UnknownOption(option.to_string()));
+        }
+    }
+
+    let mut options = MqttOptions::new(client_id, host, port);
+    if let Some((user, pass)) = credentials {
+        options = options.set_security_opts(SecurityOptions::UsernamePassword(user, pass));
+    }
+    if let Some(ca_path) = ca_path {
+        let ca = read_tls_file(&ca_path)?;
+        let client_cert = match (client_cert_path, client_key_path) {
+            (Some(cert_path), Some(key_path)) => {
+                Some((read_tls_file(&cert_path)?, read_tls_file(&key_path)?))
+            }
+            (None, None) => None,
+            _ => return Err(BrokerLineError::IncompleteClientCert),
+        };
+        options = options.set_connection_method(ConnectionMethod::Tls(ca, client_cert));
+    } else if client_cert_path.is_some() || client_key_path.is_some() {
+        return Err(BrokerLineError::ClientCertWithoutCa);
+    }
+
+    Ok((name, options))
+}
+
+fn read_tls_file(path: &str) -> Result<Vec<u8>, BrokerLineError> {
+    fs::read(path).map_err(|error| BrokerLineError::TlsFile {
+        path: path.to_string(),
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rumqtt::QoS;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn temp_file(contents: &[u8]) -> std::path::PathBuf {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let mut path = std::env::temp_dir();
+        path.push(format!(
+            "rust_mqtt_bridge_test_{}_{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn parse_broker_line_plaintext() {
+        let (name, options) = parse_broker_line("local 127.0.0.1 1883").unwrap();
+        assert_eq!(name, "local");
+        assert_eq!(options.client_id(), "local");
+        assert!(matches!(options.connection_method(), ConnectionMethod::Tcp));
+    }
+
+    #[test]
+    fn parse_broker_line_with_overrides_and_credentials() {
+        let (_, options) =
+            parse_broker_line("local 127.0.0.1 1883 client_id=bridge-1 user=alice pass=secret")
+                .unwrap();
+        assert_eq!(options.client_id(), "bridge-1");
+        assert!(matches!(
+            options.security_opts(),
+            SecurityOptions::UsernamePassword(user, pass) if user == "alice" && pass == "secret"
+        ));
+    }
+
+    #[test]
+    fn parse_broker_line_not_enough_arguments() {
+        let err = parse_broker_line("local 127.0.0.1").unwrap_err();
+        assert!(matches!(
+            err,
+            BrokerLineError::NotEnoughArguments { required: 3, given: 2 }
+        ));
+    }
+
+    #[test]
+    fn parse_broker_line_unknown_option() {
+        let err = parse_broker_line("local 127.0.0.1 1883 bogus").unwrap_err();
+        assert!(matches!(err, BrokerLineError::UnknownOption(opt) if opt == "bogus"));
+    }
+
+    #[test]
+    fn parse_broker_line_tls_with_ca() {
+        let ca = temp_file(b"fake-ca-pem");
+        let line = format!("local 127.0.0.1 8883 ca={}", ca.display());
+        let (_, options) = parse_broker_line(&line).unwrap();
+        assert!(matches!(
+            options.connection_method(),
+            ConnectionMethod::Tls(ca_bytes, None) if ca_bytes == b"fake-ca-pem"
+        ));
+    }
+
+    #[test]
+    fn parse_broker_line_tls_with_client_cert() {
+        let ca = temp_file(b"ca");
+        let cert = temp_file(b"cert");
+        let key = temp_file(b"key");
+        let line = format!(
+            "local 127.0.0.1 8883 ca={} cert={} key={}",
+            ca.display(),
+            cert.display(),
+            key.display()
+        );
+        let (_, options) = parse_broker_line(&line).unwrap();
+        assert!(matches!(
+            options.connection_method(),
+            ConnectionMethod::Tls(_, Some((cert_bytes, key_bytes)))
+                if cert_bytes == b"cert" && key_bytes == b"key"
+        ));
+    }
+
+    #[test]
+    fn parse_broker_line_cert_without_key_is_rejected() {
+        let ca = temp_file(b"ca");
+        let cert = temp_file(b"cert");
+        let line = format!("local 127.0.0.1 8883 ca={} cert={}", ca.display(), cert.display());
+        let err = parse_broker_line(&line).unwrap_err();
+        assert!(matches!(err, BrokerLineError::IncompleteClientCert));
+    }
+
+    #[test]
+    fn parse_broker_line_client_cert_without_ca_is_rejected() {
+        let cert = temp_file(b"cert");
+        let key = temp_file(b"key");
+        let line = format!("local 127.0.0.1 8883 cert={} key={}", cert.display(), key.display());
+        let err = parse_broker_line(&line).unwrap_err();
+        assert!(matches!(err, BrokerLineError::ClientCertWithoutCa));
+    }
+
+    #[test]
+    fn parse_broker_line_missing_ca_file_is_reported() {
+        let line = "local 127.0.0.1 8883 ca=/nonexistent/path/for/rust_mqtt_bridge_test";
+        let err = parse_broker_line(line).unwrap_err();
+        assert!(matches!(err, BrokerLineError::TlsFile { .. }));
+    }
+
+    #[test]
+    fn from_str_parses_brokers_and_merges_routes_on_same_topic() {
+        let content = "\
+[brokers]
+a 127.0.0.1 1883
+b 127.0.0.1 1884
+
+[routes]
+a sensors/temp b out/temp 1
+a sensors/temp b out/temp2 0
+";
+        let conf = BridgeConfiguration::from_str(content).unwrap();
+        assert_eq!(conf.mqtt_options.len(), 2);
+        assert_eq!(conf.routes[0].len(), 1);
+        assert_eq!(conf.routes[0][0].dests.len(), 2);
+        assert_eq!(conf.routes[0][0].dests[0].qos, QoS::AtLeastOnce);
+        assert_eq!(conf.routes[0][0].dests[1].qos, QoS::AtMostOnce);
+    }
+
+    #[test]
+    fn from_str_wires_hash_partition_mode_from_route_lines() {
+        let content = "\
+[brokers]
+a 127.0.0.1 1883
+b 127.0.0.1 1884
+c 127.0.0.1 1885
+
+[routes]
+a sensors/temp b out/temp 1 mode=hash
+a sensors/temp c out/temp 1 mode=hash
+";
+        let conf = BridgeConfiguration::from_str(content).unwrap();
+        assert_eq!(conf.routes[0][0].dests.len(), 2);
+        assert!(matches!(
+            conf.routes[0][0].mode,
+            crate::route::RouteMode::HashPartition(crate::route::HashKey::Topic)
+        ));
+    }
+
+    #[test]
+    fn from_str_rejects_conflicting_route_mode_for_same_topic() {
+        let content = "\
+[brokers]
+a 127.0.0.1 1883
+b 127.0.0.1 1884
+c 127.0.0.1 1885
+
+[routes]
+a sensors/temp b out/temp 1
+a sensors/temp c out/temp 1 mode=hash
+";
+        let err = BridgeConfiguration::from_str(content).unwrap_err();
+        assert!(matches!(err, ConfigError::ConflictingRouteMode { line: 8, .. }));
+    }
+
+    #[test]
+    fn from_str_rejects_duplicate_broker_names() {
+        let content = "\
+[brokers]
+a 127.0.0.1 1883
+a 127.0.0.1 1884
+";
+        let err = BridgeConfiguration::from_str(content).unwrap_err();
+        assert!(matches!(err, ConfigError::DuplicateBroker { line: 3, .. }));
+    }
+
+    #[test]
+    fn from_str_rejects_content_before_any_section() {
+        let content = "a 127.0.0.1 1883\n";
+        let err = BridgeConfiguration::from_str(content).unwrap_err();
+        assert!(matches!(err, ConfigError::ContentBeforeSection { line: 1 }));
+    }
+
+    #[test]
+    fn from_str_rejects_unknown_section() {
+        let content = "[bogus]\n";
+        let err = BridgeConfiguration::from_str(content).unwrap_err();
+        assert!(matches!(err, ConfigError::UnexpectedSection { line: 1, .. }));
+    }
+
+    #[test]
+    fn from_str_rejects_route_referencing_unknown_client() {
+        let content = "\
+[brokers]
+a 127.0.0.1 1883
+
+[routes]
+a sensors/temp ghost out/temp 1
+";
+        let err = BridgeConfiguration::from_str(content).unwrap_err();
+        assert!(matches!(err, ConfigError::Route { line: 5, .. }));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::outbound::SharedOutboundState;
+use crate::route::Destination;
+use crate::ClientId;
+use rumqtt::MqttClient;
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of emitter worker threads sharing the outbound work. A given
+/// destination client is always routed to the same worker (see
+/// `worker_for`), so its publish order is preserved even though several
+/// workers run concurrently.
+pub const NUM_EMITTER_WORKERS: usize = 4;
+
+/// Upper bound on how many forwards a worker batches into one publish pass.
+const MAX_BATCH: usize = 64;
+/// How long a worker waits to fill a batch before publishing what it has.
+const BATCH_WINDOW: Duration = Duration::from_millis(10);
+
+/// Picks the worker a destination client's publishes are always routed
+/// through, keeping per-client publish order intact across the pool.
+pub fn worker_for(client_id: ClientId, num_workers: usize) -> usize {
+    client_id as usize % num_workers
+}
+
+/// A single resolved destination queued for publishing.
+pub struct OutboundItem {
+    pub destination: Destination,
+    pub payload: Vec<u8>,
+    pub retain: bool,
+}
+
+pub struct Emitters {
+    /// This worker's own index, i.e. the one `worker_for` must return for
+    /// every client id this worker is allowed to touch.
+    pub index: usize,
+    pub receiver: flume::Receiver<OutboundItem>,
+    /// The sending half of this worker's own `receiver`, used to requeue
+    /// items for a destination that's currently full (see `publish_batch`)
+    /// instead of blocking the worker thread on it.
+    pub resubmit: flume::Sender<OutboundItem>,
+    pub clients: Vec<MqttClient>,
+    pub outbound_states: Vec<SharedOutboundState>,
+}
+
+impl Emitters {
+    pub fn start(mut self) {
+        thread::spawn(move || {
+            self.run();
+        });
+    }
+
+    pub(crate) fn run(&mut self) {
+        loop {
+            let batch = match self.collect_batch() {
+                Some(batch) => batch,
+                None => break,
+            };
+            if !batch.is_empty() {
+                self.publish_batch(batch);
+            }
+        }
+    }
+
+    /// Drains up to `MAX_BATCH` items, waiting at most `BATCH_WINDOW` for
+    /// the first one and for the rest combined, so bursts are published as
+    /// micro-batches instead of one publish per wakeup.
+    fn collect_batch(&mut self) -> Option<Vec<OutboundItem>> {
+        let mut batch = Vec::new();
+        match self.receiver.recv_timeout(BATCH_WINDOW) {
+            Ok(item) => batch.push(item),
+            Err(flume::RecvTimeoutError::Timeout) => return Some(batch),
+            Err(flume::RecvTimeoutError::Disconnected) => return None,
+        }
+        let deadline = Instant::now() + BATCH_WINDOW;
+        while batch.len() < MAX_BATCH {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            match self.receiver.recv_timeout(remaining) {
+                Ok(item) => batch.push(item),
+                Err(_) => break,
+            }
+        }
+        Some(batch)
+    }
+
+    fn publish_batch(&mut self, batch: Vec<OutboundItem>) {
+        let mut grouped: HashMap<ClientId, Vec<OutboundItem>> = HashMap::new();
+        for item in batch {
+            grouped
+                .entry(item.destination.client_id)
+                .or_insert_with(Vec::new)
+                .push(item);
+        }
+        for (client_id, items) in grouped {
+            // A real check, not `debug_assert_eq!`: this invariant is what
+            // keeps per-client publish order intact across the worker
+            // pool, and a release build silently reordering a client's
+            // publishes is worse than the (one-comparison) cost of
+            // catching it here.
+            assert_eq!(
+                worker_for(client_id, NUM_EMITTER_WORKERS),
+                self.index,
+                "client {} is owned by a different worker; routing sent it here anyway",
+                client_id
+            );
+            let client_id = client_id as usize;
+            let mut items = items.into_iter();
+            while let Some(item) = items.next() {
+                if self.outbound_states[client_id].is_full() {
+                    // This destination isn't draining. Requeue it (and
+                    // everything still queued for it, to keep its publish
+                    // order) onto this worker's own channel instead of
+                    // sleeping here: a busy-wait would also block every
+                    // other client hashed to this worker behind a single
+                    // stuck destination, and would leave the channel this
+                    // worker reads from piling up unattended in the
+                    // meantime.
+                    let _ = self.resubmit.send(item);
+                    for rest in items.by_ref() {
+                        let _ = self.resubmit.send(rest);
+                    }
+                    break;
+                }
+                match self.outbound_states[client_id].publish(
+                    &mut self.clients[client_id],
+                    item.destination.topic.to_string(),
+                    item.destination.qos,
+                    item.retain,
+                    item.payload,
+                ) {
+                    Ok(()) => (),
+                    Err(e) => println!("Publish failed: {:?}", e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_client_id_always_picks_the_same_worker() {
+        for client_id in 0..256u16 {
+            let worker = worker_for(client_id, NUM_EMITTER_WORKERS);
+            for _ in 0..4 {
+                assert_eq!(worker_for(client_id, NUM_EMITTER_WORKERS), worker);
+            }
+        }
+    }
+
+    #[test]
+    fn worker_is_always_in_range() {
+        for client_id in 0..256u16 {
+            assert!(worker_for(client_id, NUM_EMITTER_WORKERS) < NUM_EMITTER_WORKERS);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use crate::emitter::{worker_for, OutboundItem};
+use crate::outbound::SharedOutboundState;
+use crate::route::{BasicRouter, PayloadRouter};
+use rumqtt::{Notification, Receiver};
+use std::thread;
+
+pub struct Listener {
+    pub router: BasicRouter,
+    pub receiver: Receiver<Notification>,
+    /// One sender per emitter worker; a destination's client id always
+    /// picks the same worker via `worker_for`.
+    pub emitters_channels: Vec<flume::Sender<OutboundItem>>,
+    /// Outbound publish backpressure for this client, shared with
+    /// `Emitters`, released as this client's own PUBACK/PUBCOMP
+    /// notifications come in when it is also used as a forwarding
+    /// destination.
+    pub outbound_state: SharedOutboundState,
+}
+
+impl Listener {
+    pub fn start(mut self) {
+        thread::spawn(move || {
+            self.run();
+        });
+    }
+
+    fn run(&mut self) {
+        for notification in self.receiver.iter() {
+            match notification {
+                Notification::Publish(publish) => {
+                    if let Some(forward) = self.router.route_packet(&publish) {
+                        for dest in forward.destinations {
+                            let worker = worker_for(dest.client_id, self.emitters_channels.len());
+                            let item = OutboundItem {
+                                destination: dest,
+                                payload: forward.payload.clone(),
+                                retain: forward.retain,
+                            };
+                            if let Err(e) = self.emitters_channels[worker].send(item) {
+                                println!("Packet forward failed: {:?}", e);
+                            }
+                        }
+                    }
+                }
+                Notification::PubAck(_) | Notification::PubComp(_) => {
+                    self.outbound_state.on_ack();
+                }
+                _ => (),
+            }
+        }
+    }
+}
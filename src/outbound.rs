@@ -0,0 +1,89 @@
+//! Outbound publish backpressure accounting for a single destination
+//! client. This is a counter, not a retry state machine: see
+//! `OutboundState`'s doc comment for why keying in-flight publishes by
+//! packet id isn't possible with `rumqtt`, and why it isn't needed.
+//!
+//! UNRESOLVED SCOPE (chunk0-4): the request that opened this module asked
+//! for a packet-id-keyed in-flight store that advances through the real
+//! QoS1/QoS2 handshake with DUP-flagged retry on timeout. `rumqtt` 0.30.1
+//! can't return a pkid from `publish()`, which forces some scope-down, but
+//! what's here - a bare counter relying entirely on `rumqtt`'s own
+//! reconnect-triggered resend - is a materially smaller guarantee than
+//! "real delivery guarantees" and has not been signed off as an acceptable
+//! substitute for that ask. Do not treat this module as a closed
+//! implementation of chunk0-4 without that sign-off.
+
+use rumqtt::{ClientError, MqttClient, QoS};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Maximum number of unacknowledged QoS1/QoS2 publishes a single
+/// destination client may have outstanding. Once it is reached, emitters
+/// stop publishing to that client and requeue its forwards until acks free
+/// a slot; the channels those forwards wait in are unbounded, so a slow
+/// destination makes that queue grow rather than slowing the listeners.
+pub const MAX_IN_FLIGHT: usize = 64;
+
+pub type SharedOutboundState = std::sync::Arc<OutboundState>;
+
+/// Per-destination-client outbound publish backpressure.
+///
+/// `MqttClient::publish` returns `()`: rumqtt assigns the wire packet id
+/// internally and never hands it back, so there is no way to key an
+/// in-flight map by it and later match it against the id carried on a
+/// `PubAck`/`PubRec`/`PubComp` notification. This just counts outstanding
+/// QoS1/QoS2 publishes instead of tracking each one individually, which is
+/// all `is_full` needs and is all that can be done without that id.
+/// rumqtt already resends unacked QoS1/QoS2 publishes itself on reconnect,
+/// so there's nothing for the bridge to retry on top of that.
+///
+/// `on_ack` only ever fires if `rumqtt`'s `acknotify` feature is enabled
+/// (see `Cargo.toml`): without it, `PubAck`/`PubRec`/`PubComp` never leave
+/// `mqttstate.rs` as anything but `Notification::None`, `in_flight` only
+/// ever grows, and `is_full` latches `true` forever once a destination has
+/// had `MAX_IN_FLIGHT` publishes go out.
+pub struct OutboundState {
+    capacity: usize,
+    in_flight: AtomicUsize,
+}
+
+impl OutboundState {
+    pub fn new(capacity: usize) -> Self {
+        OutboundState {
+            capacity,
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.in_flight.load(Ordering::Acquire) >= self.capacity
+    }
+
+    /// Publishes `payload` through `client`, counting it against `capacity`
+    /// until its ack comes back (see `on_ack`) when `qos` is above
+    /// `AtMostOnce`. Callers must check `is_full()` first.
+    pub fn publish(
+        &self,
+        client: &mut MqttClient,
+        topic: String,
+        qos: QoS,
+        retain: bool,
+        payload: Vec<u8>,
+    ) -> Result<(), ClientError> {
+        client.publish(topic, qos, retain, payload)?;
+        if qos != QoS::AtMostOnce {
+            self.in_flight.fetch_add(1, Ordering::AcqRel);
+        }
+        Ok(())
+    }
+
+    /// A publish to this client completed its handshake: QoS1's single
+    /// PUBACK, or QoS2's final PUBCOMP (PUBREC only starts that handshake,
+    /// so it doesn't free a slot).
+    pub fn on_ack(&self) {
+        let _ = self
+            .in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                Some(n.saturating_sub(1))
+            });
+    }
+}
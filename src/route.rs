@@ -0,0 +1,438 @@
+use crate::topic::{Topic, TopicCompare, TopicFilterError};
+use crate::ClientId;
+use mqtt311::Publish;
+use rumqtt::QoS;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+#[derive(Clone, Debug)]
+pub struct Destination {
+    pub topic: Topic,
+    pub client_id: ClientId,
+    pub qos: QoS,
+}
+
+/// A resolved republish of an incoming packet to its destinations.
+///
+/// Only `retain` is carried through from the incoming packet today;
+/// MQTT v5 properties and topic-alias metadata are not, since the client
+/// (`rumqtt`/`mqtt311`) speaks v3.1.1 end to end and never hands a
+/// `Publish` with any v5 metadata to forward in the first place.
+///
+/// UNRESOLVED SCOPE (chunk0-7): the request explicitly asked for v5 user
+/// properties, content-type, response-topic and topic-alias metadata to
+/// be carried end to end, not just retain. That plumbing was built once
+/// (`Properties`, `v5.rs`) and then removed again because `rumqtt`/
+/// `mqtt311` never populate it, so there was nothing real to carry. This
+/// retain-only struct has not been signed off as satisfying chunk0-7 as
+/// written — treat it as an open scope question, not a closed
+/// implementation of the request, until that sign-off happens (or v5
+/// support lands via a different client stack).
+pub struct PayloadForward {
+    pub destinations: Vec<Destination>,
+    pub payload: Vec<u8>,
+    /// The incoming publish's retain flag, preserved through to the
+    /// republish instead of being hardcoded to `false`.
+    pub retain: bool,
+}
+
+pub trait PayloadRouter {
+    fn route_packet(&self, incoming_packet: &Publish) -> Option<PayloadForward>;
+}
+
+#[derive(Debug)]
+pub struct SingleBasicRoute {
+    pub source_client_id: ClientId,
+    pub source_topic: Topic,
+    pub dest_client_id: ClientId,
+    pub dest_topic: Topic,
+    pub dest_qos: QoS,
+    /// Mode of the route this line contributes a destination to; see
+    /// `RouteMode`. All lines sharing a `source_client_id` and
+    /// `source_topic` must agree on it,
+    /// since it's a property of the route, not of one of its destinations.
+    pub mode: RouteMode,
+}
+
+#[derive(Debug)]
+pub enum SingleBasicRouteError {
+    NotEnoughArguments { required: u8, given: u8 },
+    UnknownClient(String),
+    InvalidQos(mqtt311::Error),
+    UnparsableQos(std::num::ParseIntError),
+    InvalidFilter(TopicFilterError),
+    UnknownOption(String),
+    InvalidHashSegmentIndex(std::num::ParseIntError),
+    InvalidHashPayloadSpec(String),
+    InvalidHashPayloadOffset(std::num::ParseIntError),
+    InvalidHashPayloadLen(std::num::ParseIntError),
+}
+
+impl SingleBasicRoute {
+    pub fn from_string(
+        clients_list: HashMap<String, ClientId>,
+        s: &str,
+    ) -> Result<SingleBasicRoute, SingleBasicRouteError> {
+        let words: Vec<_> = s.split(" ").collect();
+        let min_arg_nb = 2 + 3;
+        if words.len() < min_arg_nb as usize {
+            return Err(SingleBasicRouteError::NotEnoughArguments {
+                required: min_arg_nb,
+                given: words.len() as u8, // TODO Clamp this instead
+            });
+        }
+
+        let source_client_id = match clients_list.get(words[0]) {
+            Some(id) => id.clone(),
+            None => return Err(SingleBasicRouteError::UnknownClient(words[0].to_string())),
+        };
+        let source_topic = Topic::from(words[1]);
+        source_topic
+            .validate_filter()
+            .map_err(SingleBasicRouteError::InvalidFilter)?;
+
+        let dest_client_id = match clients_list.get(words[2]) {
+            Some(id) => id.clone(),
+            None => return Err(SingleBasicRouteError::UnknownClient(words[2].to_string())),
+        };
+        let dest_topic = Topic::from(words[3]);
+        let dest_qos = match words[4].parse::<u8>() {
+            Ok(n) => match QoS::from_u8(n) {
+                Ok(qos) => qos,
+                Err(e) => return Err(SingleBasicRouteError::InvalidQos(e)),
+            },
+            Err(e) => return Err(SingleBasicRouteError::UnparsableQos(e)),
+        };
+
+        let mut mode = RouteMode::Fanout;
+        for option in &words[5..] {
+            if let Some(value) = option.strip_prefix("mode=") {
+                mode = parse_route_mode(value)?;
+            } else {
+                return Err(SingleBasicRouteError::UnknownOption(option.to_string()));
+            }
+        }
+
+        Ok(SingleBasicRoute {
+            source_client_id,
+            source_topic,
+            dest_client_id,
+            dest_topic,
+            dest_qos,
+            mode,
+        })
+    }
+}
+
+/// Parses the value of a `mode=` route-line option: `fanout` (the
+/// default), `hash` (hash the full topic), `hash_segment:<index>` (hash
+/// the `additional_path` segment captured by a trailing `#` at `<index>`;
+/// levels matched by `+` are not captured), or
+/// `hash_payload:<offset>:<len>` (hash `<len>` payload bytes at
+/// `<offset>`).
+fn parse_route_mode(value: &str) -> Result<RouteMode, SingleBasicRouteError> {
+    if value == "fanout" {
+        return Ok(RouteMode::Fanout);
+    }
+    if value == "hash" {
+        return Ok(RouteMode::HashPartition(HashKey::Topic));
+    }
+    if let Some(index) = value.strip_prefix("hash_segment:") {
+        let index = index
+            .parse::<usize>()
+            .map_err(SingleBasicRouteError::InvalidHashSegmentIndex)?;
+        return Ok(RouteMode::HashPartition(HashKey::WildcardSegment(index)));
+    }
+    if let Some(spec) = value.strip_prefix("hash_payload:") {
+        let mut parts = spec.splitn(2, ':');
+        let (offset, len) = match (parts.next(), parts.next()) {
+            (Some(offset), Some(len)) => (offset, len),
+            _ => return Err(SingleBasicRouteError::InvalidHashPayloadSpec(spec.to_string())),
+        };
+        let offset = offset
+            .parse::<usize>()
+            .map_err(SingleBasicRouteError::InvalidHashPayloadOffset)?;
+        let len = len
+            .parse::<usize>()
+            .map_err(SingleBasicRouteError::InvalidHashPayloadLen)?;
+        return Ok(RouteMode::HashPartition(HashKey::PayloadOffset { offset, len }));
+    }
+    Err(SingleBasicRouteError::UnknownOption(format!("mode={}", value)))
+}
+
+/// What part of the incoming message a `RouteMode::HashPartition` hashes to
+/// pick a destination, so that messages sharing a key always land on the
+/// same one.
+#[derive(Clone, PartialEq, Debug)]
+pub enum HashKey {
+    /// Hash the full incoming topic string.
+    Topic,
+    /// Hash the segment at this index of the match's `additional_path`,
+    /// which holds the trailing levels matched by `#`; levels matched by
+    /// `+` are not in it.
+    WildcardSegment(usize),
+    /// Hash `len` payload bytes starting at `offset` (clamped to what the
+    /// payload actually has).
+    PayloadOffset { offset: usize, len: usize },
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum RouteMode {
+    /// Forward to every destination (the historical behaviour).
+    Fanout,
+    /// Forward to exactly one destination, selected by consistently
+    /// hashing `HashKey` over `dests`.
+    HashPartition(HashKey),
+}
+
+#[derive(Clone, Debug)]
+pub struct BasicRoute {
+    pub source_topic: Topic,
+    pub dests: Vec<Destination>,
+    pub mode: RouteMode,
+}
+
+#[derive(Clone)]
+pub struct BasicRouter {
+    pub routes: Vec<BasicRoute>,
+}
+
+fn hash_key(key: &HashKey, in_topic: &Topic, additional_path: &[String], payload: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    match key {
+        HashKey::Topic => in_topic.to_string().hash(&mut hasher),
+        HashKey::WildcardSegment(index) => {
+            if let Some(segment) = additional_path.get(*index) {
+                segment.hash(&mut hasher);
+            }
+        }
+        HashKey::PayloadOffset { offset, len } => {
+            let start = (*offset).min(payload.len());
+            let end = (*offset).saturating_add(*len).min(payload.len());
+            payload[start..end].hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// Picks which of `dests` a route in `HashPartition` mode forwards to.
+fn select_destinations<'a>(
+    route: &'a BasicRoute,
+    in_topic: &Topic,
+    additional_path: &[String],
+    payload: &[u8],
+) -> Vec<&'a Destination> {
+    match &route.mode {
+        RouteMode::Fanout => route.dests.iter().collect(),
+        RouteMode::HashPartition(key) => {
+            if route.dests.is_empty() {
+                vec![]
+            } else {
+                let hash = hash_key(key, in_topic, additional_path, payload);
+                let index = (hash % route.dests.len() as u64) as usize;
+                vec![&route.dests[index]]
+            }
+        }
+    }
+}
+
+impl PayloadRouter for BasicRouter {
+    fn route_packet(&self, incoming_packet: &Publish) -> Option<PayloadForward> {
+        let in_topic = Topic::from(incoming_packet.topic_name.as_str());
+        let mut destinations = vec![];
+        for route in self.routes.iter() {
+            let additional_path = match route.source_topic.accepts(&in_topic) {
+                TopicCompare::Mismatch => continue,
+                TopicCompare::Match { additional_path } => additional_path,
+            };
+            for dest in select_destinations(route, &in_topic, &additional_path, &incoming_packet.payload) {
+                let topic = Topic {
+                    path: [&dest.topic.path[..], &additional_path].concat().to_vec(),
+                };
+                destinations.push(Destination {
+                    topic,
+                    client_id: dest.client_id,
+                    qos: dest.qos,
+                });
+            }
+        }
+        if destinations.len() > 0 {
+            Some(PayloadForward {
+                destinations,
+                payload: (*incoming_packet.payload).clone(),
+                retain: incoming_packet.retain,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dest(id: ClientId) -> Destination {
+        Destination {
+            topic: Topic::from("out"),
+            client_id: id,
+            qos: QoS::AtMostOnce,
+        }
+    }
+
+    fn route(dests: Vec<Destination>, mode: RouteMode) -> BasicRoute {
+        BasicRoute {
+            source_topic: Topic::from("in/#"),
+            dests,
+            mode,
+        }
+    }
+
+    #[test]
+    fn fanout_selects_every_destination() {
+        let route = route(vec![dest(0), dest(1), dest(2)], RouteMode::Fanout);
+        let selected = select_destinations(&route, &Topic::from("in/a"), &[], &[]);
+        assert_eq!(selected.len(), 3);
+    }
+
+    #[test]
+    fn hash_partition_selects_exactly_one_destination() {
+        let route = route(
+            vec![dest(0), dest(1), dest(2)],
+            RouteMode::HashPartition(HashKey::Topic),
+        );
+        let selected = select_destinations(&route, &Topic::from("in/a"), &[], &[]);
+        assert_eq!(selected.len(), 1);
+    }
+
+    #[test]
+    fn hash_partition_is_stable_for_the_same_key() {
+        let route = route(
+            vec![dest(0), dest(1), dest(2), dest(3)],
+            RouteMode::HashPartition(HashKey::Topic),
+        );
+        let topic = Topic::from("in/a");
+        let first = select_destinations(&route, &topic, &[], &[])[0].client_id;
+        let second = select_destinations(&route, &topic, &[], &[])[0].client_id;
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn hash_partition_on_empty_dests_selects_nothing() {
+        let route = route(vec![], RouteMode::HashPartition(HashKey::Topic));
+        let selected = select_destinations(&route, &Topic::from("in/a"), &[], &[]);
+        assert!(selected.is_empty());
+    }
+
+    #[test]
+    fn hash_key_topic_depends_only_on_the_topic() {
+        let a = hash_key(&HashKey::Topic, &Topic::from("in/a"), &[], b"x");
+        let b = hash_key(&HashKey::Topic, &Topic::from("in/a"), &[], b"y");
+        let c = hash_key(&HashKey::Topic, &Topic::from("in/b"), &[], b"x");
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn hash_key_wildcard_segment_depends_on_the_captured_path() {
+        let path_a = vec!["a".to_string()];
+        let path_b = vec!["b".to_string()];
+        let a = hash_key(&HashKey::WildcardSegment(0), &Topic::from("in"), &path_a, b"");
+        let b = hash_key(&HashKey::WildcardSegment(0), &Topic::from("in"), &path_b, b"");
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn hash_key_wildcard_segment_out_of_range_is_stable() {
+        let a = hash_key(&HashKey::WildcardSegment(5), &Topic::from("in"), &[], b"");
+        let b = hash_key(&HashKey::WildcardSegment(5), &Topic::from("in"), &[], b"");
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn hash_key_payload_offset_hashes_the_requested_slice() {
+        let payload = b"0123456789";
+        let a = hash_key(&HashKey::PayloadOffset { offset: 2, len: 3 }, &Topic::from("in"), &[], payload);
+        let b = hash_key(&HashKey::PayloadOffset { offset: 2, len: 3 }, &Topic::from("in"), &[], payload);
+        let c = hash_key(&HashKey::PayloadOffset { offset: 5, len: 3 }, &Topic::from("in"), &[], payload);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn hash_key_payload_offset_clamps_past_the_end() {
+        let payload = b"abc";
+        // Doesn't panic even though offset+len run past the payload.
+        let _ = hash_key(&HashKey::PayloadOffset { offset: 1, len: 100 }, &Topic::from("in"), &[], payload);
+    }
+
+    #[test]
+    fn hash_key_payload_offset_does_not_overflow_near_usize_max() {
+        let payload = b"abc";
+        // offset + len would overflow usize if added directly.
+        let _ = hash_key(
+            &HashKey::PayloadOffset { offset: usize::MAX - 1, len: 10 },
+            &Topic::from("in"),
+            &[],
+            payload,
+        );
+    }
+
+    #[test]
+    fn from_string_parses_hash_mode() {
+        let mut clients = HashMap::new();
+        clients.insert("a".to_string(), 0);
+        clients.insert("b".to_string(), 1);
+        let route = SingleBasicRoute::from_string(clients, "a in/topic b out/topic 1 mode=hash").unwrap();
+        assert!(matches!(route.mode, RouteMode::HashPartition(HashKey::Topic)));
+    }
+
+    #[test]
+    fn from_string_parses_hash_segment_mode() {
+        let mut clients = HashMap::new();
+        clients.insert("a".to_string(), 0);
+        clients.insert("b".to_string(), 1);
+        let route =
+            SingleBasicRoute::from_string(clients, "a in/topic b out/topic 1 mode=hash_segment:2").unwrap();
+        assert!(matches!(
+            route.mode,
+            RouteMode::HashPartition(HashKey::WildcardSegment(2))
+        ));
+    }
+
+    #[test]
+    fn from_string_parses_hash_payload_mode() {
+        let mut clients = HashMap::new();
+        clients.insert("a".to_string(), 0);
+        clients.insert("b".to_string(), 1);
+        let route = SingleBasicRoute::from_string(
+            clients,
+            "a in/topic b out/topic 1 mode=hash_payload:4:8",
+        )
+        .unwrap();
+        assert!(matches!(
+            route.mode,
+            RouteMode::HashPartition(HashKey::PayloadOffset { offset: 4, len: 8 })
+        ));
+    }
+
+    #[test]
+    fn from_string_rejects_unknown_mode() {
+        let mut clients = HashMap::new();
+        clients.insert("a".to_string(), 0);
+        clients.insert("b".to_string(), 1);
+        let err =
+            SingleBasicRoute::from_string(clients, "a in/topic b out/topic 1 mode=bogus").unwrap_err();
+        assert!(matches!(err, SingleBasicRouteError::UnknownOption(_)));
+    }
+
+    #[test]
+    fn from_string_defaults_to_fanout() {
+        let mut clients = HashMap::new();
+        clients.insert("a".to_string(), 0);
+        clients.insert("b".to_string(), 1);
+        let route = SingleBasicRoute::from_string(clients, "a in/topic b out/topic 1").unwrap();
+        assert!(matches!(route.mode, RouteMode::Fanout));
+    }
+}
@@ -0,0 +1,101 @@
+use rumqtt::{MqttClient, QoS};
+use std::collections::{HashMap, HashSet};
+
+/// Tracks, for a single client, which routes depend on each subscribed
+/// topic filter so that overlapping routes share one SUBSCRIBE.
+pub struct SubscriptionManager {
+    subscriptions: HashMap<String, Subscription>,
+}
+
+struct Subscription {
+    #[allow(dead_code)] // kept for future resubscribe-on-reconnect support
+    qos: QoS,
+    routes: HashSet<usize>,
+}
+
+impl SubscriptionManager {
+    pub fn new() -> Self {
+        SubscriptionManager {
+            subscriptions: HashMap::new(),
+        }
+    }
+
+    /// Registers `route_index` as depending on `topic_filter`, issuing a
+    /// SUBSCRIBE on `client` only if no other route already depends on it.
+    ///
+    /// The dedup path (`sub.routes.insert` returning `true` for an already
+    /// subscribed filter) is dead with the only caller today:
+    /// `BridgeConfiguration::from_str` merges route lines by
+    /// `source_topic` per client before a single `BasicRoute` per filter
+    /// ever reaches `Bridge::start`, so `subscribe` is never invoked twice
+    /// for the same `(client, topic_filter)`. It stays as genuine support
+    /// for a caller that doesn't pre-merge, not as exercised behaviour.
+    /// See `track_subscribe` for the ref-counting logic this relies on,
+    /// tested independently of needing a live `MqttClient`.
+    pub fn subscribe(
+        &mut self,
+        client: &mut MqttClient,
+        route_index: usize,
+        topic_filter: &str,
+        qos: QoS,
+    ) -> Result<(), rumqtt::ClientError> {
+        if self.track_subscribe(route_index, topic_filter, qos) {
+            client.subscribe(topic_filter.to_string(), qos)?;
+        }
+        Ok(())
+    }
+
+    /// Pure ref-counting half of `subscribe`: records `route_index` against
+    /// `topic_filter` and reports whether a SUBSCRIBE actually needs to go
+    /// out (`true` for a never-before-seen filter, `false` when it collapsed
+    /// into an existing one). Split out from `subscribe` so this logic is
+    /// testable without an `MqttClient`.
+    fn track_subscribe(&mut self, route_index: usize, topic_filter: &str, qos: QoS) -> bool {
+        match self.subscriptions.get_mut(topic_filter) {
+            Some(sub) => {
+                if sub.routes.insert(route_index) {
+                    println!(
+                        "Duplicate subscription to '{}' collapsed (now {} routes depend on it)",
+                        topic_filter,
+                        sub.routes.len()
+                    );
+                }
+                false
+            }
+            None => {
+                let mut routes = HashSet::new();
+                routes.insert(route_index);
+                self.subscriptions
+                    .insert(topic_filter.to_string(), Subscription { qos, routes });
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_subscribe_to_a_filter_is_not_collapsed() {
+        let mut mgr = SubscriptionManager::new();
+        assert!(mgr.track_subscribe(0, "a/b", QoS::AtLeastOnce));
+    }
+
+    #[test]
+    fn second_route_on_same_filter_collapses() {
+        let mut mgr = SubscriptionManager::new();
+        assert!(mgr.track_subscribe(0, "a/b", QoS::AtLeastOnce));
+        assert!(!mgr.track_subscribe(1, "a/b", QoS::AtLeastOnce));
+        assert_eq!(mgr.subscriptions.get("a/b").unwrap().routes.len(), 2);
+    }
+
+    #[test]
+    fn resubscribing_same_route_does_not_grow_refcount() {
+        let mut mgr = SubscriptionManager::new();
+        assert!(mgr.track_subscribe(0, "a/b", QoS::AtLeastOnce));
+        assert!(!mgr.track_subscribe(0, "a/b", QoS::AtLeastOnce));
+        assert_eq!(mgr.subscriptions.get("a/b").unwrap().routes.len(), 1);
+    }
+}
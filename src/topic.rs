@@ -0,0 +1,178 @@
+#[derive(Clone, Debug)]
+pub struct Topic {
+    pub path: Vec<String>,
+}
+
+pub enum TopicCompare {
+    Mismatch,
+    Match { additional_path: Vec<String> },
+}
+
+#[derive(Debug)]
+pub enum TopicFilterError {
+    /// `#` appeared somewhere other than the last level of the filter.
+    HashNotLast,
+    /// `+` or `#` was found mixed into a level instead of occupying it
+    /// entirely (e.g. `foo+/bar`).
+    EmbeddedWildcard { level: String },
+}
+
+impl Topic {
+    pub fn to_string(&self) -> String {
+        self.path.join("/")
+    }
+
+    /// Checks that `self` is a well-formed MQTT subscription filter: `#` may
+    /// only appear as the last level, and neither `+` nor `#` may be mixed
+    /// into a level with other characters.
+    pub fn validate_filter(&self) -> Result<(), TopicFilterError> {
+        let last_index = self.path.len() - 1;
+        for (i, part) in self.path.iter().enumerate() {
+            if part.contains('#') {
+                if part != "#" {
+                    return Err(TopicFilterError::EmbeddedWildcard {
+                        level: part.clone(),
+                    });
+                }
+                if i != last_index {
+                    return Err(TopicFilterError::HashNotLast);
+                }
+            } else if part.contains('+') && part != "+" {
+                return Err(TopicFilterError::EmbeddedWildcard {
+                    level: part.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Matches `self`, a subscription filter, against `other`, a concrete
+    /// topic. `+` consumes exactly one level, `#` matches the rest of the
+    /// topic (zero or more levels) and ends the comparison.
+    pub fn accepts(&self, other: &Self) -> TopicCompare {
+        for (i, part) in self.path.iter().enumerate() {
+            if part == "#" {
+                return if i <= other.path.len() {
+                    TopicCompare::Match {
+                        additional_path: other.path[i..].to_vec(),
+                    }
+                } else {
+                    TopicCompare::Mismatch
+                };
+            }
+            match other.path.get(i) {
+                Some(other_part) if part == "+" || part == other_part => (),
+                _ => return TopicCompare::Mismatch,
+            }
+        }
+        if self.path.len() == other.path.len() {
+            TopicCompare::Match {
+                additional_path: vec![],
+            }
+        } else {
+            TopicCompare::Mismatch
+        }
+    }
+}
+
+impl From<&str> for Topic {
+    fn from(s: &str) -> Self {
+        Topic {
+            path: s.split("/").map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn additional_path(compare: TopicCompare) -> Option<Vec<String>> {
+        match compare {
+            TopicCompare::Match { additional_path } => Some(additional_path),
+            TopicCompare::Mismatch => None,
+        }
+    }
+
+    #[test]
+    fn accepts_exact_match() {
+        let filter = Topic::from("a/b/c");
+        let topic = Topic::from("a/b/c");
+        assert_eq!(additional_path(filter.accepts(&topic)), Some(vec![]));
+    }
+
+    #[test]
+    fn accepts_rejects_mismatched_level() {
+        let filter = Topic::from("a/b/c");
+        let topic = Topic::from("a/x/c");
+        assert!(additional_path(filter.accepts(&topic)).is_none());
+    }
+
+    #[test]
+    fn accepts_rejects_filter_longer_than_topic() {
+        let filter = Topic::from("a/b/c");
+        let topic = Topic::from("a/b");
+        assert!(additional_path(filter.accepts(&topic)).is_none());
+    }
+
+    #[test]
+    fn accepts_rejects_topic_longer_than_filter_without_hash() {
+        let filter = Topic::from("a/b");
+        let topic = Topic::from("a/b/c");
+        assert!(additional_path(filter.accepts(&topic)).is_none());
+    }
+
+    #[test]
+    fn accepts_plus_matches_exactly_one_level() {
+        let filter = Topic::from("a/+/c");
+        let topic = Topic::from("a/b/c");
+        assert_eq!(additional_path(filter.accepts(&topic)), Some(vec![]));
+
+        let topic = Topic::from("a/b/x/c");
+        assert!(additional_path(filter.accepts(&topic)).is_none());
+    }
+
+    #[test]
+    fn accepts_hash_matches_zero_or_more_trailing_levels() {
+        let filter = Topic::from("a/#");
+
+        assert_eq!(
+            additional_path(filter.accepts(&Topic::from("a"))),
+            Some(vec![])
+        );
+        assert_eq!(
+            additional_path(filter.accepts(&Topic::from("a/b"))),
+            Some(vec!["b".to_string()])
+        );
+        assert_eq!(
+            additional_path(filter.accepts(&Topic::from("a/b/c"))),
+            Some(vec!["b".to_string(), "c".to_string()])
+        );
+    }
+
+    #[test]
+    fn validate_filter_accepts_plain_and_wildcard_filters() {
+        assert!(Topic::from("a/b/c").validate_filter().is_ok());
+        assert!(Topic::from("a/+/c").validate_filter().is_ok());
+        assert!(Topic::from("a/b/#").validate_filter().is_ok());
+        assert!(Topic::from("#").validate_filter().is_ok());
+    }
+
+    #[test]
+    fn validate_filter_rejects_hash_not_last() {
+        let err = Topic::from("a/#/c").validate_filter().unwrap_err();
+        assert!(matches!(err, TopicFilterError::HashNotLast));
+    }
+
+    #[test]
+    fn validate_filter_rejects_embedded_hash() {
+        let err = Topic::from("a/foo#").validate_filter().unwrap_err();
+        assert!(matches!(err, TopicFilterError::EmbeddedWildcard { .. }));
+    }
+
+    #[test]
+    fn validate_filter_rejects_embedded_plus() {
+        let err = Topic::from("a/foo+bar/c").validate_filter().unwrap_err();
+        assert!(matches!(err, TopicFilterError::EmbeddedWildcard { .. }));
+    }
+}